@@ -0,0 +1,408 @@
+//! Extracts `extern` block signatures from Rust source, built on top of
+//! [`crate::lexer`], and flags foreign function calls made outside of
+//! `unsafe`.
+//!
+//! Like the rest of this crate, extraction is zero-copy: the pieces of a
+//! signature ([`ForeignItem::name`], parameter and return types) are slices
+//! of the original source rather than freshly rendered text, so callers get
+//! back exactly what was written (spacing and all).
+
+use crate::lexer::{self, Token, TokenKind};
+use std::ops::Range;
+
+/// One `extern "ABI" { ... }` block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternBlock<'a> {
+	/// The ABI string, e.g. `"C"` in `extern "C" { .. }` becomes `C`.
+	/// Defaults to `"C"` for a bare `extern { .. }`.
+	pub abi: &'a str,
+	/// The `name` from a `#[link(name = "...")]` attribute immediately
+	/// preceding the block, if any.
+	pub link_name: Option<&'a str>,
+	pub items: Vec<ForeignItem<'a>>,
+	/// The byte range covered by the attribute (if any) and the block.
+	pub span: Range<usize>,
+}
+
+/// One `fn` declaration inside an [`ExternBlock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignItem<'a> {
+	/// The symbol name, e.g. `fabs`.
+	pub name: &'a str,
+	/// The source text of each parameter's type, in order.
+	pub params: Vec<&'a str>,
+	/// Whether the declaration ends in a C-style `...` variadic parameter.
+	pub variadic: bool,
+	/// The source text of the return type, or `None` when the declaration
+	/// has no `->` (an explicit `-> ()` is kept as `Some("()")`).
+	pub return_type: Option<&'a str>,
+	pub span: Range<usize>,
+}
+
+/// A foreign function call found outside of an `unsafe` block or function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsafeCallDiagnostic<'a> {
+	/// The symbol being called.
+	pub symbol: &'a str,
+	/// The span of the call's callee, e.g. `fabs` in `fabs(x)`.
+	pub span: Range<usize>,
+}
+
+/// Finds every `extern "ABI" { ... }` block in `src`.
+pub fn extern_blocks(src: &str) -> Vec<ExternBlock<'_>> {
+	let tokens = lexer::tokenize(src);
+	let kept: Vec<&Token> = tokens.iter().filter(|t| t.kind != TokenKind::Whitespace).collect();
+
+	let mut blocks = Vec::new();
+	let mut i = 0;
+	while i < kept.len() {
+		if text(&kept, src, i) != "extern" {
+			i += 1;
+			continue;
+		}
+
+		let mut j = i + 1;
+		let abi = if kept.get(j).map(|t| t.kind.clone()) == Some(TokenKind::StringLiteral) {
+			let abi = strip_string_literal(text(&kept, src, j));
+			j += 1;
+			abi
+		} else {
+			"C"
+		};
+
+		if text(&kept, src, j) != "{" {
+			// Not a block, e.g. `extern "C" fn foo();`; not our concern here.
+			i += 1;
+			continue;
+		}
+		let Some(close) = find_close(&kept, src, j) else {
+			i += 1;
+			continue;
+		};
+
+		let (link_name, attr_start) = preceding_link_attr(&kept, src, i).unwrap_or((None, i));
+		let span = kept[attr_start].span.start..kept[close].span.end;
+		let items = parse_foreign_items(&kept, src, j + 1, close);
+
+		blocks.push(ExternBlock { abi, link_name, items, span });
+		i = close + 1;
+	}
+	blocks
+}
+
+/// Finds every call to one of `blocks`' symbols in `src` that isn't inside
+/// an `unsafe` block or `unsafe fn`.
+pub fn unwrapped_calls<'a>(src: &'a str, blocks: &[ExternBlock<'a>]) -> Vec<UnsafeCallDiagnostic<'a>> {
+	let symbols: Vec<&str> = blocks.iter().flat_map(|b| b.items.iter().map(|item| item.name)).collect();
+	let block_spans: Vec<Range<usize>> = blocks.iter().map(|b| b.span.clone()).collect();
+
+	let tokens = lexer::tokenize(src);
+	let kept: Vec<&Token> = tokens.iter().filter(|t| t.kind != TokenKind::Whitespace).collect();
+
+	let mut diagnostics = Vec::new();
+	// `unsafe_depths[n]` is true if brace-nesting level `n` is inside an
+	// `unsafe` block or `unsafe fn`/`unsafe impl` body. A nested level
+	// inherits its parent's unsafe-ness.
+	let mut unsafe_depths: Vec<bool> = vec![false];
+	let mut pending_unsafe = false;
+
+	let mut i = 0;
+	while i < kept.len() {
+		let tok = kept[i];
+		let t = text(&kept, src, i);
+		match t {
+			"unsafe" if tok.kind == TokenKind::Ident => pending_unsafe = true,
+			"{" => {
+				let inherited = *unsafe_depths.last().unwrap();
+				unsafe_depths.push(pending_unsafe || inherited);
+				pending_unsafe = false;
+			}
+			"}" => {
+				if unsafe_depths.len() > 1 {
+					unsafe_depths.pop();
+				}
+			}
+			";" => pending_unsafe = false,
+			_ => {
+				if tok.kind == TokenKind::Ident
+					&& symbols.contains(&t)
+					&& text(&kept, src, i + 1) == "("
+					&& !block_spans.iter().any(|s| s.contains(&tok.span.start))
+					&& !*unsafe_depths.last().unwrap()
+				{
+					diagnostics.push(UnsafeCallDiagnostic { symbol: t, span: tok.span.clone() });
+				}
+			}
+		}
+		i += 1;
+	}
+	diagnostics
+}
+
+// --- parsing helpers -------------------------------------------------------
+
+fn text<'a>(kept: &[&Token], src: &'a str, i: usize) -> &'a str {
+	match kept.get(i) {
+		Some(t) => &src[t.span.clone()],
+		None => "",
+	}
+}
+
+fn strip_string_literal(text: &str) -> &str {
+	text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text)
+}
+
+/// Finds the index of the `}` that closes the `{` at `open_idx`.
+fn find_close(kept: &[&Token], src: &str, open_idx: usize) -> Option<usize> {
+	let mut depth = 1usize;
+	let mut i = open_idx + 1;
+	while i < kept.len() {
+		match text(kept, src, i) {
+			"{" => depth += 1,
+			"}" => {
+				depth -= 1;
+				if depth == 0 {
+					return Some(i);
+				}
+			}
+			_ => {}
+		}
+		i += 1;
+	}
+	None
+}
+
+/// If a `#[link(name = "...")]` attribute sits immediately before the
+/// `extern` keyword at `extern_idx` (skipping comments), returns its `name`
+/// value (if present) and the index of the attribute's opening `#`.
+fn preceding_link_attr<'a>(
+	kept: &[&Token],
+	src: &'a str,
+	extern_idx: usize,
+) -> Option<(Option<&'a str>, usize)> {
+	let mut j = extern_idx;
+	while j > 0 && is_comment_kind(&kept[j - 1].kind) {
+		j -= 1;
+	}
+	if j == 0 || text(kept, src, j - 1) != "]" {
+		return None;
+	}
+	let close = j - 1;
+
+	let mut depth = 1i32;
+	let mut k = close;
+	while k > 0 {
+		k -= 1;
+		match text(kept, src, k) {
+			"]" => depth += 1,
+			"[" => {
+				depth -= 1;
+				if depth == 0 {
+					break;
+				}
+			}
+			_ => {}
+		}
+	}
+	if depth != 0 || k == 0 || text(kept, src, k - 1) != "#" {
+		return None;
+	}
+	let attr_start = k - 1;
+
+	let inner = &kept[k + 1..close];
+	if inner.first().map(|t| &src[t.span.clone()]) != Some("link") {
+		return None;
+	}
+	let mut link_name = None;
+	let mut m = 1;
+	while m < inner.len() {
+		if &src[inner[m].span.clone()] == "name"
+			&& inner.get(m + 1).map(|t| &src[t.span.clone()]) == Some("=")
+		{
+			if let Some(tok) = inner.get(m + 2) {
+				if tok.kind == TokenKind::StringLiteral {
+					link_name = Some(strip_string_literal(&src[tok.span.clone()]));
+				}
+			}
+		}
+		m += 1;
+	}
+	Some((link_name, attr_start))
+}
+
+/// Parses `fn` declarations in `kept[start..end]` (the inside of an extern
+/// block). Anything that isn't recognized as a `fn` declaration is skipped
+/// rather than treated as an error, the same resynchronizing spirit as the
+/// lexer's own error recovery.
+fn parse_foreign_items<'a>(kept: &[&Token], src: &'a str, start: usize, end: usize) -> Vec<ForeignItem<'a>> {
+	let mut items = Vec::new();
+	let mut i = start;
+	while i < end {
+		if text(kept, src, i) != "fn" {
+			i += 1;
+			continue;
+		}
+		let item_start = kept[i].span.start;
+		i += 1;
+		let name = text(kept, src, i);
+		i += 1;
+		if text(kept, src, i) != "(" {
+			continue;
+		}
+		i += 1;
+
+		let mut params = Vec::new();
+		let mut variadic = false;
+		while i < end && text(kept, src, i) != ")" {
+			if text(kept, src, i) == "..." {
+				variadic = true;
+				i += 1;
+				continue;
+			}
+			// `name : Type`; skip the binding name and colon.
+			if text(kept, src, i + 1) == ":" {
+				i += 2;
+			}
+			let ty_start = i;
+			let mut depth = 0i32;
+			// A parameter's type can itself contain a comma, inside a generic
+			// (`HashMap<String, i32>`); track angle-bracket depth alongside
+			// `(`/`[`/`{` so that comma doesn't get mistaken for the
+			// parameter separator. `>>` closes two levels at once, as in
+			// `Vec<Vec<i32>>`.
+			let mut angle_depth = 0i32;
+			while i < end {
+				match text(kept, src, i) {
+					"(" | "[" | "{" => depth += 1,
+					")" | "]" | "}" if depth > 0 => depth -= 1,
+					"<" => angle_depth += 1,
+					">" if angle_depth > 0 => angle_depth -= 1,
+					">>" if angle_depth > 0 => angle_depth -= angle_depth.min(2),
+					"," | ")" if depth == 0 && angle_depth == 0 => break,
+					_ => {}
+				}
+				i += 1;
+			}
+			if i > ty_start {
+				params.push(&src[kept[ty_start].span.start..kept[i - 1].span.end]);
+			}
+			if text(kept, src, i) == "," {
+				i += 1;
+			}
+		}
+		if text(kept, src, i) == ")" {
+			i += 1;
+		}
+
+		let return_type = if text(kept, src, i) == "->" {
+			i += 1;
+			let ty_start = i;
+			let mut depth = 0i32;
+			while i < end {
+				match text(kept, src, i) {
+					"(" | "[" | "{" => depth += 1,
+					")" | "]" | "}" if depth > 0 => depth -= 1,
+					";" if depth == 0 => break,
+					_ => {}
+				}
+				i += 1;
+			}
+			(i > ty_start).then(|| &src[kept[ty_start].span.start..kept[i - 1].span.end])
+		} else {
+			None
+		};
+
+		let item_end = kept.get(i).map(|t| t.span.end).unwrap_or(item_start);
+		if text(kept, src, i) == ";" {
+			i += 1;
+		}
+
+		items.push(ForeignItem { name, params, variadic, return_type, span: item_start..item_end });
+	}
+	items
+}
+
+fn is_comment_kind(kind: &TokenKind) -> bool {
+	matches!(
+		kind,
+		TokenKind::LineComment
+			| TokenKind::DocLineComment { .. }
+			| TokenKind::BlockComment
+			| TokenKind::DocBlockComment { .. }
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn extracts_basic_signature() {
+		let src = r#"extern "C" { fn fabs(x: f64) -> f64; }"#;
+		let blocks = extern_blocks(src);
+		assert_eq!(blocks.len(), 1);
+		assert_eq!(blocks[0].abi, "C");
+		assert_eq!(blocks[0].link_name, None);
+		let item = &blocks[0].items[0];
+		assert_eq!(item.name, "fabs");
+		assert_eq!(item.params, vec!["f64"]);
+		assert!(!item.variadic);
+		assert_eq!(item.return_type, Some("f64"));
+	}
+
+	#[test]
+	fn parses_variadic_and_non_c_abi_and_link_attr() {
+		let src = r#"
+#[link(name = "m")]
+extern "system" {
+	fn printf(fmt: *const i8, ...) -> i32;
+}
+"#;
+		let blocks = extern_blocks(src);
+		assert_eq!(blocks.len(), 1);
+		assert_eq!(blocks[0].abi, "system");
+		assert_eq!(blocks[0].link_name, Some("m"));
+		let item = &blocks[0].items[0];
+		assert_eq!(item.name, "printf");
+		assert_eq!(item.params, vec!["*const i8"]);
+		assert!(item.variadic);
+		assert_eq!(item.return_type, Some("i32"));
+	}
+
+	#[test]
+	fn generic_param_type_is_not_split_on_its_inner_comma() {
+		let src = r#"extern "C" { fn bar(x: HashMap<String, i32>); }"#;
+		let blocks = extern_blocks(src);
+		let item = &blocks[0].items[0];
+		assert_eq!(item.params, vec!["HashMap<String, i32>"]);
+	}
+
+	#[test]
+	fn nested_generic_param_type_is_not_split() {
+		let src = r#"extern "C" { fn baz(x: HashMap<String, Vec<i32>>, y: i32); }"#;
+		let blocks = extern_blocks(src);
+		let item = &blocks[0].items[0];
+		assert_eq!(item.params, vec!["HashMap<String, Vec<i32>>", "i32"]);
+	}
+
+	#[test]
+	fn flags_foreign_call_not_wrapped_in_unsafe() {
+		let src = r#"
+extern "C" {
+	fn fabs(x: f64) -> f64;
+}
+
+unsafe fn call_fabs(x: f64) -> f64 {
+	unsafe { fabs(x) }
+}
+
+fn bad_call(x: f64) -> f64 {
+	fabs(x)
+}
+"#;
+		let blocks = extern_blocks(src);
+		let diagnostics = unwrapped_calls(src, &blocks);
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(diagnostics[0].symbol, "fabs");
+	}
+}
@@ -0,0 +1,797 @@
+//! A rustfmt-style formatting subsystem built on top of [`crate::lexer`].
+//!
+//! [`format_str`] re-lays out Rust source text: it normalizes indentation
+//! and inter-token spacing, and inserts trailing commas in multi-line match
+//! arms and macro invocations. It does not reflow single-line constructs
+//! into multiple lines (and vice versa) purely to fit `max_width` — whether
+//! a bracketed construct is laid out across multiple lines is decided by
+//! whether the *input* already spans multiple lines there, the same way a
+//! human author's choice to write `{ a + b }` versus a multi-statement
+//! block is preserved rather than overridden. The same principle extends to
+//! an item's signature even where it isn't bracketed at all: an item body's
+//! opening `{` that the input already put on its own line (as after a
+//! multi-line `where` clause) stays there rather than being glued to the
+//! signature's last token. This keeps formatting
+//! idempotent: formatting already-formatted output reproduces it exactly.
+
+use crate::lexer::{self, Token, TokenKind};
+use std::ops::Range;
+
+/// How a formatted file should be indented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+	Tabs,
+	Spaces(usize),
+}
+
+/// Configuration for [`format_str`] and [`check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FmtConfig {
+	pub indent: Indent,
+	pub max_width: usize,
+}
+
+impl Default for FmtConfig {
+	fn default() -> Self {
+		FmtConfig { indent: Indent::Spaces(4), max_width: 100 }
+	}
+}
+
+/// An error that prevents [`format_str`] from producing output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FmtError {
+	/// The lexer could not tokenize this span of the input.
+	InvalidToken { span: Range<usize> },
+	/// Delimiters in the input don't balance, so there's no well-formed
+	/// block structure to lay out.
+	UnbalancedDelimiters { span: Range<usize> },
+}
+
+/// The result of [`check`]: either the input was already formatted, or a
+/// line-oriented diff of what would change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FmtCheck {
+	Formatted,
+	Diff(String),
+}
+
+/// Reformat `src` according to `config`.
+pub fn format_str(src: &str, config: &FmtConfig) -> Result<String, FmtError> {
+	let tokens = lexer::tokenize(src);
+	for t in &tokens {
+		if t.kind == TokenKind::Error {
+			return Err(FmtError::InvalidToken { span: t.span.clone() });
+		}
+	}
+	let kept: Vec<&Token> = tokens.iter().filter(|t| t.kind != TokenKind::Whitespace).collect();
+	let close_of = match_delimiters(&kept, src)?;
+	let opens = classify_opens(&kept, src, &close_of);
+	Ok(Printer::new(config).run(&kept, src, &close_of, &opens))
+}
+
+/// Check whether `src` is already formatted according to `config`, without
+/// rewriting anything. Suitable for wiring into CI.
+pub fn check(src: &str, config: &FmtConfig) -> Result<FmtCheck, FmtError> {
+	let formatted = format_str(src, config)?;
+	if formatted == src {
+		Ok(FmtCheck::Formatted)
+	} else {
+		Ok(FmtCheck::Diff(line_diff(src, &formatted)))
+	}
+}
+
+// --- delimiter matching and classification ---------------------------------
+
+fn open_char(s: &str) -> Option<char> {
+	match s {
+		"{" | "(" | "[" => s.chars().next(),
+		_ => None,
+	}
+}
+
+fn matches_pair(open: char, close: &str) -> bool {
+	matches!((open, close), ('{', "}") | ('(', ")") | ('[', "]"))
+}
+
+/// Maps the index (into `kept`) of each opening delimiter to the index of
+/// its matching closing delimiter.
+fn match_delimiters(kept: &[&Token], src: &str) -> Result<Vec<Option<usize>>, FmtError> {
+	let mut close_of = vec![None; kept.len()];
+	let mut stack: Vec<(char, usize)> = Vec::new();
+	for (i, t) in kept.iter().enumerate() {
+		let text = &src[t.span.clone()];
+		if let Some(c) = open_char(text) {
+			stack.push((c, i));
+		} else if matches!(text, "}" | ")" | "]") {
+			match stack.pop() {
+				Some((open, open_idx)) if matches_pair(open, text) => {
+					close_of[open_idx] = Some(i);
+				}
+				_ => return Err(FmtError::UnbalancedDelimiters { span: t.span.clone() }),
+			}
+		}
+	}
+	if let Some(&(_, open_idx)) = stack.last() {
+		return Err(FmtError::UnbalancedDelimiters { span: kept[open_idx].span.clone() });
+	}
+	Ok(close_of)
+}
+
+/// What a bracketed region represents, for the purposes of comma handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ctx {
+	/// A `match` expression's arm list.
+	Match,
+	/// A macro invocation's argument list (`name!(...)`, `name![...]`, `name!{...}`).
+	MacroCall,
+	/// An attribute's argument list: `#[derive(...)]`, `#![allow(...)]`.
+	Attr,
+	/// Anything else: function bodies, struct/enum bodies, call args, etc.
+	Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenInfo {
+	ctx: Ctx,
+	is_multiline: bool,
+	/// Whether this `{` opens an item's body (`fn`/`struct`/`enum`/`trait`/
+	/// `impl`/`mod`/`extern`), as opposed to a block expression, call args,
+	/// or a struct literal. Closing such a brace always ends the item, even
+	/// one whose body stayed on one line, so two sibling items never get
+	/// mashed onto the same output line regardless of nesting depth.
+	is_item_body: bool,
+	/// Whether this `{` is a `use` import list (`use a::b::{c, d};`),
+	/// recognized by directly following `::`. Unlike a block expression's
+	/// `{ a + b }`, an import list doesn't get padded with inner spaces.
+	is_use_list: bool,
+}
+
+fn classify_opens(kept: &[&Token], src: &str, close_of: &[Option<usize>]) -> Vec<Option<OpenInfo>> {
+	let mut opens = vec![None; kept.len()];
+	for (i, t) in kept.iter().enumerate() {
+		let text = &src[t.span.clone()];
+		if open_char(text).is_none() {
+			continue;
+		}
+		let Some(close_idx) = close_of[i] else { continue };
+
+		let is_multiline = src[t.span.end..kept[close_idx].span.start].contains('\n');
+
+		let is_attr = text == "["
+			&& ((i > 0 && &src[kept[i - 1].span.clone()] == "#")
+				|| (i > 1
+					&& &src[kept[i - 1].span.clone()] == "!"
+					&& &src[kept[i - 2].span.clone()] == "#"));
+		let preceded_by_bang =
+			i > 0 && kept[i - 1].kind == TokenKind::Punct && &src[kept[i - 1].span.clone()] == "!";
+		let is_match_arm = text == "{" && brace_is_match_arm(kept, src, i);
+		let ctx = if is_attr {
+			Ctx::Attr
+		} else if preceded_by_bang {
+			Ctx::MacroCall
+		} else if is_match_arm {
+			Ctx::Match
+		} else {
+			Ctx::Other
+		};
+		let is_item_body =
+			text == "{" && !is_attr && !preceded_by_bang && !is_match_arm && brace_is_item_body(kept, src, i);
+		let is_use_list = text == "{" && i > 0 && &src[kept[i - 1].span.clone()] == "::";
+
+		opens[i] = Some(OpenInfo { ctx, is_multiline, is_item_body, is_use_list });
+	}
+	opens
+}
+
+/// Whether the `{` at `open_idx` is a `match` expression's arm block: scans
+/// backward over the scrutinee expression (allowing balanced `(`/`[`
+/// nesting) looking for a leading `match` keyword, stopping at the first
+/// statement/block boundary.
+fn brace_is_match_arm(kept: &[&Token], src: &str, open_idx: usize) -> bool {
+	let mut depth = 0i32;
+	let mut j = open_idx;
+	while j > 0 {
+		j -= 1;
+		let tok = kept[j];
+		let text = &src[tok.span.clone()];
+		match text {
+			")" | "]" => depth += 1,
+			"(" | "[" => {
+				if depth == 0 {
+					return false;
+				}
+				depth -= 1;
+			}
+			"{" | "}" | ";" if depth == 0 => return false,
+			"match" if depth == 0 && tok.kind == TokenKind::Ident => return true,
+			_ => {}
+		}
+	}
+	false
+}
+
+/// Whether the `{` at `open_idx` opens an item's body: scans backward over
+/// its signature (allowing balanced `(`/`[` nesting, for parameter lists and
+/// generic `where` bounds) looking for a leading `fn`/`struct`/`enum`/
+/// `trait`/`impl`/`mod`/`extern`/`union` keyword, stopping at the first
+/// statement/block boundary — the same approach as [`brace_is_match_arm`].
+fn brace_is_item_body(kept: &[&Token], src: &str, open_idx: usize) -> bool {
+	let mut depth = 0i32;
+	let mut j = open_idx;
+	while j > 0 {
+		j -= 1;
+		let tok = kept[j];
+		let text = &src[tok.span.clone()];
+		match text {
+			")" | "]" => depth += 1,
+			"(" | "[" => {
+				if depth == 0 {
+					return false;
+				}
+				depth -= 1;
+			}
+			"{" | "}" | ";" if depth == 0 => return false,
+			_ if depth == 0 && tok.kind == TokenKind::Ident => {
+				if matches!(text, "fn" | "struct" | "enum" | "trait" | "impl" | "mod" | "extern" | "union") {
+					return true;
+				}
+			}
+			_ => {}
+		}
+	}
+	false
+}
+
+// --- printing ----------------------------------------------------------------
+
+struct BlockState {
+	ctx: Ctx,
+	is_multiline: bool,
+	/// True for the pseudo-block pushed for a closure parameter list
+	/// (`|x, y|`), so that its params don't inherit the enclosing block's
+	/// one-item-per-line comma handling.
+	is_pipe: bool,
+	/// Mirrors [`OpenInfo::is_item_body`].
+	is_item_body: bool,
+	/// Mirrors [`OpenInfo::is_use_list`].
+	is_use_list: bool,
+}
+
+struct Printer<'c> {
+	config: &'c FmtConfig,
+	out: String,
+	depth: usize,
+	stack: Vec<BlockState>,
+	at_line_start: bool,
+}
+
+impl<'c> Printer<'c> {
+	fn new(config: &'c FmtConfig) -> Self {
+		Printer { config, out: String::new(), depth: 0, stack: Vec::new(), at_line_start: true }
+	}
+
+	fn write_indent(&mut self) {
+		match self.config.indent {
+			Indent::Tabs => {
+				for _ in 0..self.depth {
+					self.out.push('\t');
+				}
+			}
+			Indent::Spaces(n) => {
+				for _ in 0..self.depth * n {
+					self.out.push(' ');
+				}
+			}
+		}
+	}
+
+	/// Starts a new line, preserving a single blank line if the gap between
+	/// `prev` and `next` in the original source contained one.
+	fn break_line(&mut self, prev: &Token, next: &Token, src: &str) {
+		let gap = &src[prev.span.end..next.span.start];
+		let blank = gap.matches('\n').count() >= 2;
+		self.out.push('\n');
+		if blank {
+			self.out.push('\n');
+		}
+		self.write_indent();
+		self.at_line_start = true;
+	}
+
+	fn run(mut self, kept: &[&Token], src: &str, close_of: &[Option<usize>], opens: &[Option<OpenInfo>]) -> String {
+		let mut i = 0;
+		while i < kept.len() {
+			let tok = kept[i];
+			let text = &src[tok.span.clone()];
+			let is_comment = is_comment_kind(&tok.kind);
+			let is_close = matches!(text, "}" | ")" | "]");
+
+			if !self.at_line_start && text == "#" {
+				self.break_line(kept[i - 1], tok, src);
+			}
+
+			// An item body's opening brace (`impl ... where\n\tT: ...,\n{`)
+			// goes on its own line when the source already had a line break
+			// right before it, the same
+			// existing-multiline-preservation principle used inside
+			// brackets, just applied to a signature that isn't bracketed at
+			// all (e.g. a trailing `where` clause).
+			let is_item_body_open = text == "{" && opens.get(i).copied().flatten().map(|o| o.is_item_body).unwrap_or(false);
+			if is_item_body_open && !self.at_line_start && src[kept[i - 1].span.end..tok.span.start].contains('\n') {
+				self.break_line(kept[i - 1], tok, src);
+			}
+
+			if !self.at_line_start && needs_space_before(src, kept, i, close_of, &self.stack) {
+				self.out.push(' ');
+			}
+			self.at_line_start = false;
+
+			if is_close {
+				let block = self.stack.pop();
+				if let Some(block) = block {
+					let open_idx = find_open_for_close(close_of, i);
+					let empty_body = open_idx.map(|o| o + 1 == i).unwrap_or(false);
+					if block.is_multiline && !empty_body {
+						if matches!(block.ctx, Ctx::Match | Ctx::MacroCall) && !ends_with_comma_or_open(&self.out) {
+							self.out.push(',');
+						}
+						self.depth -= 1;
+						self.break_line(kept[i - 1], tok, src);
+						self.out.push_str(text);
+					} else {
+						if !block.is_multiline && !block.is_use_list && text == "}" && !empty_body {
+							self.out.push(' ');
+						}
+						self.out.push_str(text);
+					}
+					// A closed multi-line block, a finished attribute, a
+					// closed item body (ending an item, even one whose own
+					// body stayed on one line, at any nesting depth), or a
+					// close that the source already followed with a line
+					// break always ends the line it's on, unless what follows is
+					// itself a continuation token (`,`, `;`, a further
+					// closer, or `else`).
+					if let Some(next) = kept.get(i + 1) {
+						let source_break = src[tok.span.end..next.span.start].contains('\n');
+						let force_break =
+							block.is_multiline || matches!(block.ctx, Ctx::Attr) || block.is_item_body || source_break;
+						if force_break {
+							let next_text = &src[next.span.clone()];
+							let continues = matches!(next_text, "," | ";" | ")" | "]" | "}")
+								|| (next_text == "else" && next.kind == TokenKind::Ident);
+							if !continues {
+								self.break_line(tok, next, src);
+							}
+						}
+					}
+				} else {
+					self.out.push_str(text);
+				}
+			} else {
+				self.out.push_str(text);
+			}
+
+			if let Some(info) = opens.get(i).copied().flatten() {
+				self.stack.push(BlockState {
+					ctx: info.ctx,
+					is_multiline: info.is_multiline,
+					is_pipe: false,
+					is_item_body: info.is_item_body,
+					is_use_list: info.is_use_list,
+				});
+				let close_idx = close_of[i];
+				let empty_body = close_idx.map(|c| c == i + 1).unwrap_or(true);
+				if info.is_multiline && !empty_body {
+					self.depth += 1;
+					if let Some(next) = kept.get(i + 1) {
+						self.break_line(tok, next, src);
+					}
+				}
+				// The space after an inline `{` is `needs_space_before`'s job
+				// (it fires for the very next token), not ours — pushing it
+				// here too would double it up.
+			} else if text == ";" {
+				let inline_block = self.stack.last().map(|b| !b.is_multiline).unwrap_or(false);
+				if !inline_block {
+					// If the next token closes the current block, let the
+					// close-token handling own that line break (with the
+					// correctly dedented indent) instead of breaking twice.
+					if let Some(next) = kept.get(i + 1).filter(|n| !matches!(&src[n.span.clone()], "}" | ")" | "]")) {
+						self.break_line(tok, next, src);
+					}
+				}
+			} else if text == "," {
+				let top_multiline = self.stack.last().map(|b| b.is_multiline).unwrap_or(false);
+				if top_multiline {
+					if let Some(next) = kept.get(i + 1).filter(|n| !matches!(&src[n.span.clone()], "}" | ")" | "]")) {
+						self.break_line(tok, next, src);
+					}
+				}
+			} else if is_comment {
+				if let Some(next) = kept.get(i + 1) {
+					let next_text = &src[next.span.clone()];
+					let next_is_else = next_text == "else" && next.kind == TokenKind::Ident;
+					let next_is_close = matches!(next_text, "}" | ")" | "]");
+					if !next_is_else && !next_is_close {
+						self.break_line(tok, next, src);
+					}
+				}
+			} else if matches!(text, "*" | "+") && tok.kind == TokenKind::Punct && is_postfix_kleene_star(kept, src, close_of, i) {
+				// A Kleene operator closing a `$(...)` repetition (`),*`)
+				// binds tightly to its closing delimiter, so the close-token
+				// handling above doesn't get a chance to preserve an existing
+				// line break after it; do that here instead.
+				if let Some(next) = kept.get(i + 1) {
+					let next_text = &src[next.span.clone()];
+					let continues = matches!(next_text, "," | ";" | ")" | "]" | "}")
+						|| (next_text == "else" && next.kind == TokenKind::Ident);
+					if !continues && src[tok.span.end..next.span.start].contains('\n') {
+						self.break_line(tok, next, src);
+					}
+				}
+			} else if text == "|" && tok.kind == TokenKind::Punct {
+				// Closure parameter lists (`|x, y|`) aren't real brackets, but
+				// their commas must not inherit the enclosing block's
+				// one-item-per-line treatment, so track them as a pseudo-block.
+				let closes_pipe = self.stack.last().map(|b| b.is_pipe).unwrap_or(false);
+				if closes_pipe {
+					self.stack.pop();
+				} else if i > 0 && !prev_ends_operand(kept[i - 1].kind.clone(), &src[kept[i - 1].span.clone()]) {
+					self.stack.push(BlockState {
+						ctx: Ctx::Other,
+						is_multiline: false,
+						is_pipe: true,
+						is_item_body: false,
+						is_use_list: false,
+					});
+				}
+			}
+
+			i += 1;
+		}
+		self.out
+	}
+}
+
+fn find_open_for_close(close_of: &[Option<usize>], close_idx: usize) -> Option<usize> {
+	close_of.iter().position(|c| *c == Some(close_idx))
+}
+
+fn ends_with_comma_or_open(out: &str) -> bool {
+	let trimmed = out.trim_end();
+	trimmed.ends_with(',') || trimmed.ends_with('{') || trimmed.ends_with('(') || trimmed.ends_with('[')
+}
+
+fn is_comment_kind(kind: &TokenKind) -> bool {
+	matches!(
+		kind,
+		TokenKind::LineComment
+			| TokenKind::DocLineComment { .. }
+			| TokenKind::BlockComment
+			| TokenKind::DocBlockComment { .. }
+	)
+}
+
+/// Rough, intentionally conservative spacing rules between adjacent kept
+/// tokens. Errs toward inserting a space, since a missing space is far more
+/// likely to be visually wrong than an extra one.
+///
+/// Precedence matters here: e.g. "space after a separator" must be checked
+/// before "no space before an opening paren", so that `foo(a, (b, c))` gets
+/// a space between the comma and the nested `(`.
+fn needs_space_before(src: &str, kept: &[&Token], i: usize, close_of: &[Option<usize>], stack: &[BlockState]) -> bool {
+	let cur = kept[i];
+	let prev = kept[i - 1];
+	let cur_text = &src[cur.span.clone()];
+	let prev_text = &src[prev.span.clone()];
+
+	// Immediately after an inline `{`, pad with a space: `{ a + b }`. A
+	// `use` import list (`use a::b::{c, d};`) isn't a block expression and
+	// doesn't get this padding.
+	if prev_text == "{" {
+		let top = stack.last();
+		if top.map(|b| b.is_use_list).unwrap_or(false) {
+			return false;
+		}
+		if top.map(|b| !b.is_multiline).unwrap_or(false) {
+			return true;
+		}
+	}
+
+	// Nothing hugs a closing/terminal token with a space before it.
+	if matches!(cur_text, "," | ";" | ")" | "]" | "}" | "?") {
+		return false;
+	}
+	// A Kleene operator closing a `$(...)` repetition binds tightly to
+	// whatever precedes it (`),*`), unlike an ordinary token after a
+	// separator, so it must be checked before the blanket "space after a
+	// separator" rule below fires on the comma/semicolon in front of it.
+	if matches!(cur_text, "*" | "+") && cur.kind == TokenKind::Punct && is_postfix_kleene_star(kept, src, close_of, i) {
+		return false;
+	}
+	// A macro-fragment specifier's colon (`$x:expr`) binds tightly on both
+	// sides, unlike an ordinary type-ascription colon (`x: i32`).
+	if prev_text == ":" && i >= 3 {
+		let name = kept[i - 2];
+		let sigil = &src[kept[i - 3].span.clone()];
+		if name.kind == TokenKind::Ident && sigil == "$" {
+			return false;
+		}
+	}
+	// A separator always gets a space after it.
+	if matches!(prev_text, "," | ";" | ":") {
+		return true;
+	}
+	// No space right after an opener or an attribute `#`.
+	if matches!(prev_text, "(" | "[" | "#") {
+		return false;
+	}
+	// No space before a call/index/attribute opener.
+	if matches!(cur_text, "(" | "[") {
+		return false;
+	}
+	// `::`, `.`, and range operators bind tightly on both sides: `a.b`,
+	// `x::y`, `0..3`.
+	if matches!(prev_text, "::" | "." | ".." | "..=") || matches!(cur_text, "::" | "." | ".." | "..=") {
+		return false;
+	}
+	// Closure parameter delimiters bind tightly to what's *inside* them
+	// (`|x, y|`), but not to what's outside (`move |y| x`, not `move|y|x`).
+	// `stack` already tracks whether we're currently between an opening and
+	// closing `|` (pushed/popped in `Printer::run`), so a `|` adjacent to
+	// that state is the one closing or opening the pair; a `|` seen while
+	// not in that state is the other side, which binds loosely instead.
+	let inside_pipe = stack.last().map(|b| b.is_pipe).unwrap_or(false);
+	if (prev_text == "|" || cur_text == "|") && inside_pipe {
+		return false;
+	}
+	// `name!` (macro invocation) binds tightly; a standalone prefix `!x`
+	// needs a space before it unless it's already hugging an operator or
+	// open delimiter handled above.
+	if cur_text == "!" && cur.kind == TokenKind::Punct {
+		let is_macro_bang = matches!(prev.kind, TokenKind::Ident | TokenKind::Lifetime) && !is_prefix_keyword(prev_text);
+		if is_macro_bang {
+			return false;
+		}
+	}
+	// `$` is a macro-fragment sigil (`$x`, `$(...)`) and always binds
+	// tightly to what follows it, unlike the other prefix sigils below it's
+	// never also a binary operator.
+	if prev_text == "$" && prev.kind == TokenKind::Punct {
+		return false;
+	}
+	// Unary prefix sigils (`&x`, `&mut x`, `*x`, `!x`, `-x`) bind to what
+	// follows; the same characters used as binary operators (`x * y`,
+	// `x - y`) don't. A `*` Kleene operator closing a `$(...)` repetition
+	// (`),*`) is neither — it's postfix, so it must not be mistaken for a
+	// unary prefix on whatever comes next.
+	if matches!(prev_text, "&" | "*" | "!" | "-") && prev.kind == TokenKind::Punct {
+		let prev_is_prefix = if prev_text == "*" && is_postfix_kleene_star(kept, src, close_of, i - 1) {
+			false
+		} else if i < 2 {
+			true
+		} else {
+			let before = kept[i - 2];
+			!prev_ends_operand(before.kind.clone(), &src[before.span.clone()])
+		};
+		if prev_is_prefix {
+			return false;
+		}
+	}
+	// Generic angle brackets bind tightly to their contents: `Vec<T>`,
+	// `longest<'a>`. A `<` always opens one; a closing `>` only stays tight
+	// against what follows when that's plainly a continuation of the same
+	// path/call (`Vec::<T>::new()`, `fn f<T>(...)`), not e.g. `= vec![...]`.
+	if cur_text == "<" || cur_text == ">" {
+		return false;
+	}
+	if prev_text == "<" {
+		return false;
+	}
+	if prev_text == ">" && matches!(cur_text, "(" | "::" | "," | ")" | ";") {
+		return false;
+	}
+	if cur_text == ":" {
+		return false;
+	}
+	true
+}
+
+/// Keywords after which `&`, `*`, or `!` is a prefix operator rather than a
+/// binary one, even though they lex as a plain [`TokenKind::Ident`].
+fn is_prefix_keyword(text: &str) -> bool {
+	matches!(
+		text,
+		"return" | "if" | "while" | "match" | "let" | "in" | "else" | "move" | "yield" | "break" | "continue" | "for" | "loop"
+	)
+}
+
+/// Whether a token of this kind/text would plausibly end an operand, i.e. a
+/// `&`/`*`/`!` immediately after it is a binary/comparison operator rather
+/// than a unary prefix.
+fn prev_ends_operand(kind: TokenKind, text: &str) -> bool {
+	match kind {
+		TokenKind::Ident => !is_prefix_keyword(text),
+		TokenKind::Lifetime
+		| TokenKind::IntLiteral
+		| TokenKind::FloatLiteral
+		| TokenKind::CharLiteral
+		| TokenKind::ByteLiteral
+		| TokenKind::StringLiteral
+		| TokenKind::ByteStringLiteral
+		| TokenKind::RawStringLiteral { .. }
+		| TokenKind::RawByteStringLiteral { .. } => true,
+		TokenKind::Punct => matches!(text, ")" | "]" | "}"),
+		_ => false,
+	}
+}
+
+/// Whether the `*` at `star_idx` is the Kleene operator closing a `$(...)`
+/// macro-matcher repetition (`),*`) rather than a dereference/multiplication
+/// operator. That's the case when it directly follows the repetition's
+/// closing `)` — possibly with a single literal separator token in between
+/// (e.g. the `,` in `$( $x:expr ),*`) — *and* that `)` actually closes a
+/// `$(`-opened group, not just any parenthesized expression or call; a
+/// lookback that only checked "is there a `)` two tokens back" would also
+/// fire on ordinary code like `rc.borrow_mut(); *v += 1;`.
+fn is_postfix_kleene_star(kept: &[&Token], src: &str, close_of: &[Option<usize>], star_idx: usize) -> bool {
+	if star_idx == 0 {
+		return false;
+	}
+	let close_idx = if &src[kept[star_idx - 1].span.clone()] == ")" {
+		star_idx - 1
+	} else if star_idx >= 2
+		&& &src[kept[star_idx - 2].span.clone()] == ")"
+		&& !matches!(&src[kept[star_idx - 1].span.clone()], "*" | "+" | "?")
+	{
+		star_idx - 2
+	} else {
+		return false;
+	};
+	let Some(open_idx) = find_open_for_close(close_of, close_idx) else { return false };
+	open_idx > 0 && &src[kept[open_idx - 1].span.clone()] == "$"
+}
+
+// --- diff --------------------------------------------------------------------
+
+/// A minimal line-oriented diff (classic LCS), good enough for surfacing a
+/// CI-readable "what would `format_str` change" report.
+fn line_diff(a: &str, b: &str) -> String {
+	let a_lines: Vec<&str> = a.lines().collect();
+	let b_lines: Vec<&str> = b.lines().collect();
+	let n = a_lines.len();
+	let m = b_lines.len();
+
+	let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lcs[i][j] = if a_lines[i] == b_lines[j] {
+				lcs[i + 1][j + 1] + 1
+			} else {
+				lcs[i + 1][j].max(lcs[i][j + 1])
+			};
+		}
+	}
+
+	let mut out = String::new();
+	let (mut i, mut j) = (0, 0);
+	while i < n && j < m {
+		if a_lines[i] == b_lines[j] {
+			i += 1;
+			j += 1;
+		} else if lcs[i + 1][j] >= lcs[i][j + 1] {
+			out.push('-');
+			out.push_str(a_lines[i]);
+			out.push('\n');
+			i += 1;
+		} else {
+			out.push('+');
+			out.push_str(b_lines[j]);
+			out.push('\n');
+			j += 1;
+		}
+	}
+	for line in &a_lines[i..n] {
+		out.push('-');
+		out.push_str(line);
+		out.push('\n');
+	}
+	for line in &b_lines[j..m] {
+		out.push('+');
+		out.push_str(line);
+		out.push('\n');
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn fmt(src: &str) -> String {
+		format_str(src, &FmtConfig::default()).unwrap()
+	}
+
+	#[test]
+	fn inline_block_gets_exactly_one_space_on_each_side() {
+		assert_eq!(fmt("fn add(a: i32, b: i32) -> i32 { a + b }"), "fn add(a: i32, b: i32) -> i32 { a + b }");
+	}
+
+	#[test]
+	fn consecutive_inline_item_bodies_stay_one_per_line() {
+		let src = "pub fn add(a: i32, b: i32) -> i32 { a + b }\npub fn sub(a: i32, b: i32) -> i32 { a - b }\n";
+		let out = fmt(src);
+		assert_eq!(out.lines().count(), 2, "got: {out:?}");
+		assert_eq!(out, "pub fn add(a: i32, b: i32) -> i32 { a + b }\npub fn sub(a: i32, b: i32) -> i32 { a - b }");
+	}
+
+	#[test]
+	fn generic_param_list_keeps_space_before_block_brace() {
+		let src = "pub struct Point<T> {\n\tx: T,\n}\n";
+		assert!(fmt(src).starts_with("pub struct Point<T> {\n"));
+
+		let src = "pub trait Summable<T> {\n\tfn sum(&self) -> T;\n}\n";
+		assert!(fmt(src).starts_with("pub trait Summable<T> {\n"));
+	}
+
+	#[test]
+	fn formatting_is_idempotent_on_mixed_inline_and_multiline_items() {
+		let src = "pub mod m {\n\tpub fn add(a: i32, b: i32) -> i32 { a + b }\n\tpub fn sub(a: i32, b: i32) -> i32 { a - b }\n}\n";
+		let once = fmt(src);
+		let twice = fmt(&once);
+		assert_eq!(once, twice);
+	}
+
+	#[test]
+	fn closure_params_stay_tight_but_space_outside() {
+		assert_eq!(fmt("let add = |x: i32, y: i32| x + y;"), "let add = |x: i32, y: i32| x + y;");
+		assert_eq!(fmt("let f = move |y| x + y;"), "let f = move |y| x + y;");
+		assert_eq!(fmt("let f = || 0;"), "let f = || 0;");
+	}
+
+	#[test]
+	fn unary_minus_binds_to_its_operand() {
+		assert_eq!(fmt("call_fabs(-3.14);"), "call_fabs(-3.14);");
+		assert_eq!(fmt("let x = -y;"), "let x = -y;");
+		assert_eq!(fmt("let x = a - b;"), "let x = a - b;");
+	}
+
+	#[test]
+	fn where_clause_keeps_item_body_brace_on_its_own_line() {
+		let src = "impl<T> Summable<T> for Vec<T>\nwhere\n\tT: std::ops::Add<Output = T> + Default + Copy,\n{\n\tfn sum(&self) -> T {\n\t\tT::default()\n\t}\n}\n";
+		let out = fmt(src);
+		assert!(out.contains("Copy,\n{\n"), "got: {out:?}");
+		let twice = fmt(&out);
+		assert_eq!(out, twice);
+	}
+
+	#[test]
+	fn use_import_list_is_not_padded_like_a_block_expression() {
+		assert_eq!(fmt("use math::ops::{add as add_i32, sub};"), "use math::ops::{add as add_i32, sub};");
+	}
+
+	#[test]
+	fn dereference_assignment_after_a_call_is_not_mistaken_for_a_kleene_op() {
+		assert_eq!(fmt("rc.borrow_mut(); *v += 1;"), "rc.borrow_mut();\n*v += 1;");
+		assert_eq!(fmt("f(x); *y = 1;"), "f(x);\n*y = 1;");
+	}
+
+	#[test]
+	fn macro_rules_dollar_sigils_and_kleene_ops_are_not_mangled() {
+		// The `my_vec!` macro from `examples/example.rs`.
+		let src = "macro_rules! my_vec {\n\t( $( $x:expr ),* $(,)? ) => {\n\t\t{\n\t\t\tlet mut temp_vec = Vec::new();\n\t\t\t$( temp_vec.push($x); )*\n\t\t\ttemp_vec\n\t\t}\n\t};\n}\n";
+		let once = fmt(src);
+		assert_eq!(
+			once,
+			"macro_rules! my_vec {\n    ($($x:expr),* $(,)?) => {\n        {\n            let mut temp_vec = Vec::new();\n            $(temp_vec.push($x);)*\n            temp_vec\n        }\n    };\n}"
+		);
+		let twice = fmt(&once);
+		assert_eq!(once, twice);
+	}
+
+	#[test]
+	fn inline_block_close_keeps_existing_break_before_next_statement() {
+		let src = "fn f() -> i32 {\n\t{ do_it(); }\n\tx\n}\n";
+		assert_eq!(fmt(src), "fn f() -> i32 {\n    { do_it(); }\n    x\n}");
+	}
+}
@@ -0,0 +1,604 @@
+//! A zero-copy tokenizer for Rust source text.
+//!
+//! This is a hand-written lexer in the spirit of a formal grammar (think an
+//! ANTLR `RustLexer.g4`): every token production below corresponds to a rule
+//! in that grammar, implemented as a method on [`Lexer`]. Tokens never own
+//! source text; they carry a byte [`Range`] that the caller slices out of
+//! the original `&str`.
+//!
+//! The lexer is infallible: malformed input never panics. Anything it can't
+//! make sense of is emitted as a single [`TokenKind::Error`] token, and
+//! scanning resumes at the next whitespace boundary.
+
+use std::ops::Range;
+use std::str::CharIndices;
+
+/// The kind of a lexical token.
+///
+/// Keyword/identifier disambiguation (`fn` vs. a user identifier) is left to
+/// the parser, matching how rustc's own lexer behaves: both come back as
+/// [`TokenKind::Ident`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+	/// An identifier or keyword, e.g. `fn`, `x`, `r#match`.
+	Ident,
+	/// A lifetime or loop label, without the leading `'`'s quote handling
+	/// being visible to callers, e.g. `'a`, `'outer`.
+	Lifetime,
+	/// An integer literal, e.g. `0`, `1_000`, `0xFF`, `42u32`.
+	IntLiteral,
+	/// A floating point literal, e.g. `1.0`, `3.14f64`, `1e10`.
+	FloatLiteral,
+	/// A character literal, e.g. `'x'`, `'\n'`.
+	CharLiteral,
+	/// A byte literal, e.g. `b'x'`.
+	ByteLiteral,
+	/// A string literal, e.g. `"hi"`.
+	StringLiteral,
+	/// A byte string literal, e.g. `b"hi"`.
+	ByteStringLiteral,
+	/// A raw string literal, e.g. `r"hi"`, `r#"hi"#`. `hashes` is the number
+	/// of `#` characters used as the delimiter.
+	RawStringLiteral { hashes: usize },
+	/// A raw byte string literal, e.g. `br"hi"`, `br#"hi"#`.
+	RawByteStringLiteral { hashes: usize },
+	/// A `//` line comment that is not a doc comment.
+	LineComment,
+	/// A `///` or `//!` doc comment. `inner` is true for `//!`.
+	DocLineComment { inner: bool },
+	/// A `/* */` block comment that is not a doc comment.
+	BlockComment,
+	/// A `/** */` or `/*! */` doc comment. `inner` is true for `/*! */`.
+	DocBlockComment { inner: bool },
+	/// A `#!...` shebang. Only recognized on line 1 of the source, and only
+	/// when not immediately followed by `[` (which is an inner attribute,
+	/// `#![...]`, not a shebang).
+	Shebang,
+	/// A run of whitespace (spaces, tabs, newlines).
+	Whitespace,
+	/// Punctuation or an operator, e.g. `+`, `::`, `->`, `..=`.
+	Punct,
+	/// Input the lexer could not make sense of. Scanning resynchronizes at
+	/// the next whitespace character (or EOF) after an error.
+	Error,
+}
+
+/// A single lexical token: its kind, and the byte range it occupies in the
+/// source string that was passed to [`tokenize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+	pub kind: TokenKind,
+	pub span: Range<usize>,
+}
+
+/// Multi-character operators, longest first so that max-munch picks e.g.
+/// `..=` over `..` over `.`, and `->` over `-`.
+const OPERATORS: &[&str] = &[
+	"<<=", ">>=", "..=", "...",
+	"::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||",
+	"+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=", "<<", ">>", "..",
+];
+
+/// Tokenize `src` into a vector of zero-copy [`Token`]s.
+///
+/// The lexer never panics: malformed input produces [`TokenKind::Error`]
+/// tokens rather than an `Err`, so this always returns a complete token
+/// stream covering the whole input.
+pub fn tokenize(src: &str) -> Vec<Token> {
+	Lexer::new(src).run()
+}
+
+struct Lexer<'a> {
+	src: &'a str,
+	chars: std::iter::Peekable<CharIndices<'a>>,
+	len: usize,
+}
+
+impl<'a> Lexer<'a> {
+	fn new(src: &'a str) -> Self {
+		Lexer { src, chars: src.char_indices().peekable(), len: src.len() }
+	}
+
+	fn run(mut self) -> Vec<Token> {
+		let mut tokens = Vec::new();
+
+		// A shebang is only a shebang on the very first line, and only when
+		// it isn't actually an inner attribute (`#![...]`).
+		if self.src.starts_with("#!") && !self.src.starts_with("#![") {
+			let end = self.src.find('\n').unwrap_or(self.len);
+			tokens.push(Token { kind: TokenKind::Shebang, span: 0..end });
+			self.advance_to(end);
+		}
+
+		while let Some(&(start, c)) = self.chars.peek() {
+			let kind = if c.is_whitespace() {
+				self.lex_whitespace()
+			} else if c == '/' {
+				self.lex_slash()
+			} else if c == '\'' {
+				self.lex_quote()
+			} else if c == '"' {
+				self.lex_string(start)
+			} else if c == 'r' && self.peek_nth(1) == Some('#') && self.peek_nth(2).is_some_and(is_ident_start) {
+				self.lex_raw_ident()
+			} else if c == 'r' && self.peek_is_raw_string_start(1) {
+				self.lex_raw_string(start, false)
+			} else if c == 'b' && self.peek_nth(1) == Some('r') && self.peek_is_raw_string_start(2)
+			{
+				self.lex_raw_string(start, true)
+			} else if c == 'b' && self.peek_nth(1) == Some('\'') {
+				self.lex_byte_char()
+			} else if c == 'b' && self.peek_nth(1) == Some('"') {
+				self.lex_byte_string()
+			} else if c.is_ascii_digit() {
+				self.lex_number()
+			} else if is_ident_start(c) {
+				self.lex_ident()
+			} else if let Some(op) = self.match_operator() {
+				op
+			} else if is_ascii_punct(c) {
+				self.bump();
+				TokenKind::Punct
+			} else {
+				self.lex_error()
+			};
+			// Any error token (e.g. a malformed char literal) runs on to the
+			// next whitespace or EOF, so scanning resynchronizes there.
+			if kind == TokenKind::Error && !self.src[start..self.pos()].ends_with(char::is_whitespace) {
+				while matches!(self.chars.peek(), Some(&(_, c)) if !c.is_whitespace()) {
+					self.bump();
+				}
+			}
+			let end = self.pos();
+			tokens.push(Token { kind, span: start..end });
+		}
+
+		tokens
+	}
+
+	// --- low-level cursor helpers -----------------------------------------
+
+	fn pos(&mut self) -> usize {
+		match self.chars.peek() {
+			Some(&(i, _)) => i,
+			None => self.len,
+		}
+	}
+
+	fn bump(&mut self) -> Option<char> {
+		self.chars.next().map(|(_, c)| c)
+	}
+
+	fn peek_nth(&self, n: usize) -> Option<char> {
+		self.chars.clone().nth(n).map(|(_, c)| c)
+	}
+
+	fn advance_to(&mut self, byte_pos: usize) {
+		while let Some(&(i, _)) = self.chars.peek() {
+			if i >= byte_pos {
+				break;
+			}
+			self.chars.next();
+		}
+	}
+
+	fn peek_is_raw_string_start(&self, offset: usize) -> bool {
+		let mut i = offset;
+		while self.peek_nth(i) == Some('#') {
+			i += 1;
+		}
+		self.peek_nth(i) == Some('"')
+	}
+
+	// --- token productions --------------------------------------------------
+
+	fn lex_whitespace(&mut self) -> TokenKind {
+		while matches!(self.chars.peek(), Some(&(_, c)) if c.is_whitespace()) {
+			self.bump();
+		}
+		TokenKind::Whitespace
+	}
+
+	fn lex_slash(&mut self) -> TokenKind {
+		match self.peek_nth(1) {
+			Some('/') => self.lex_line_comment(),
+			Some('*') => self.lex_block_comment(),
+			_ => {
+				self.bump();
+				TokenKind::Punct
+			}
+		}
+	}
+
+	fn lex_line_comment(&mut self) -> TokenKind {
+		self.bump(); // first '/'
+		self.bump(); // second '/'
+		let inner = self.chars.peek().map(|&(_, c)| c) == Some('!');
+		// `///` is a doc comment, but `////...` (4+ slashes) is a plain
+		// comment, same rule rustc uses.
+		let doc_line = self.chars.peek().map(|&(_, c)| c) == Some('/')
+			&& self.peek_nth(1) != Some('/');
+		while matches!(self.chars.peek(), Some(&(_, c)) if c != '\n') {
+			self.bump();
+		}
+		if inner {
+			TokenKind::DocLineComment { inner: true }
+		} else if doc_line {
+			TokenKind::DocLineComment { inner: false }
+		} else {
+			TokenKind::LineComment
+		}
+	}
+
+	fn lex_block_comment(&mut self) -> TokenKind {
+		self.bump(); // '/'
+		self.bump(); // '*'
+		let inner = self.chars.peek().map(|&(_, c)| c) == Some('!');
+		let doc_block = self.chars.peek().map(|&(_, c)| c) == Some('*')
+			&& self.peek_nth(1) != Some('*')
+			&& self.peek_nth(1) != Some('/');
+		let mut depth = 1usize;
+		while depth > 0 {
+			match self.bump() {
+				Some('/') if self.chars.peek().map(|&(_, c)| c) == Some('*') => {
+					self.bump();
+					depth += 1;
+				}
+				Some('*') if self.chars.peek().map(|&(_, c)| c) == Some('/') => {
+					self.bump();
+					depth -= 1;
+				}
+				Some(_) => {}
+				None => break, // unterminated; treat EOF as the close
+			}
+		}
+		if inner {
+			TokenKind::DocBlockComment { inner: true }
+		} else if doc_block {
+			TokenKind::DocBlockComment { inner: false }
+		} else {
+			TokenKind::BlockComment
+		}
+	}
+
+	/// Lexes either a char literal (`'x'`) or a lifetime/label (`'a`,
+	/// `'outer`), applying max-munch: a single char immediately followed by
+	/// a closing `'` is a char literal, anything else beginning with an
+	/// identifier character is a lifetime.
+	fn lex_quote(&mut self) -> TokenKind {
+		self.bump(); // opening '\''
+
+		if self.chars.peek().map(|&(_, c)| c) == Some('\\') {
+			self.bump();
+			// Escape sequence; consume one escaped char (or unicode escape
+			// braces) then the closing quote.
+			if self.chars.peek().map(|&(_, c)| c) == Some('u') {
+				self.bump();
+				if self.chars.peek().map(|&(_, c)| c) == Some('{') {
+					while matches!(self.chars.peek(), Some(&(_, c)) if c != '}') {
+						self.bump();
+					}
+					self.bump();
+				}
+			} else {
+				self.bump();
+			}
+			return if self.chars.peek().map(|&(_, c)| c) == Some('\'') {
+				self.bump();
+				TokenKind::CharLiteral
+			} else {
+				TokenKind::Error
+			};
+		}
+
+		match self.chars.peek().copied() {
+			Some((_, c)) if is_ident_start(c) => {
+				// Could still be a one-char literal like 'x'.
+				if self.peek_nth(1) == Some('\'') {
+					self.bump();
+					self.bump();
+					TokenKind::CharLiteral
+				} else {
+					self.bump();
+					while matches!(self.chars.peek(), Some(&(_, c)) if is_ident_continue(c)) {
+						self.bump();
+					}
+					TokenKind::Lifetime
+				}
+			}
+			Some(_) => {
+				// Any other single character followed by a closing quote,
+				// e.g. `' '`, `'''`.
+				self.bump();
+				if self.chars.peek().map(|&(_, c)| c) == Some('\'') {
+					self.bump();
+					TokenKind::CharLiteral
+				} else {
+					TokenKind::Error
+				}
+			}
+			None => TokenKind::Error,
+		}
+	}
+
+	fn lex_string(&mut self, _start: usize) -> TokenKind {
+		self.bump(); // opening quote
+		self.consume_string_body();
+		TokenKind::StringLiteral
+	}
+
+	fn lex_byte_string(&mut self) -> TokenKind {
+		self.bump(); // 'b'
+		self.bump(); // opening quote
+		self.consume_string_body();
+		TokenKind::ByteStringLiteral
+	}
+
+	fn lex_byte_char(&mut self) -> TokenKind {
+		self.bump(); // 'b'
+		match self.lex_quote() {
+			TokenKind::CharLiteral => TokenKind::ByteLiteral,
+			other => other,
+		}
+	}
+
+	fn consume_string_body(&mut self) {
+		while let Some(&(_, c)) = self.chars.peek() {
+			match c {
+				'"' => {
+					self.bump();
+					return;
+				}
+				'\\' => {
+					self.bump();
+					self.bump();
+				}
+				_ => {
+					self.bump();
+				}
+			}
+		}
+		// Unterminated string: EOF closes it implicitly.
+	}
+
+	fn lex_raw_string(&mut self, _start: usize, is_byte: bool) -> TokenKind {
+		if is_byte {
+			self.bump(); // 'b'
+		}
+		self.bump(); // 'r'
+		let mut hashes = 0;
+		while self.chars.peek().map(|&(_, c)| c) == Some('#') {
+			self.bump();
+			hashes += 1;
+		}
+		self.bump(); // opening quote
+
+		loop {
+			match self.bump() {
+				Some('"') => {
+					// Need exactly `hashes` '#' characters to close.
+					let mut matched = 0;
+					while matched < hashes && self.chars.peek().map(|&(_, c)| c) == Some('#') {
+						self.bump();
+						matched += 1;
+					}
+					if matched == hashes {
+						break;
+					}
+				}
+				Some(_) => {}
+				None => break, // unterminated
+			}
+		}
+
+		if is_byte {
+			TokenKind::RawByteStringLiteral { hashes }
+		} else {
+			TokenKind::RawStringLiteral { hashes }
+		}
+	}
+
+	/// Lexes a raw identifier, e.g. `r#match`, as a single [`TokenKind::Ident`]
+	/// spanning `r#` and the identifier that follows, so that keyword-shaped
+	/// raw identifiers round-trip through the same token kind as any other
+	/// identifier. Only reached when the `#` isn't a raw string's delimiter
+	/// (that's ruled out by the caller checking what follows the `#`).
+	fn lex_raw_ident(&mut self) -> TokenKind {
+		self.bump(); // 'r'
+		self.bump(); // '#'
+		while matches!(self.chars.peek(), Some(&(_, c)) if is_ident_continue(c)) {
+			self.bump();
+		}
+		TokenKind::Ident
+	}
+
+	/// Lexes an integer or float literal, taking care that `0..3` lexes as
+	/// `0`, `..`, `3` rather than consuming the `.` into a float.
+	fn lex_number(&mut self) -> TokenKind {
+		// Only the integer part itself, not a type suffix or an exponent's
+		// `e`/`E` — those are handled explicitly below so that `1e10` and
+		// `1e+10` aren't swallowed whole by a generic alnum scan before we
+		// get a chance to recognize them as exponents.
+		while matches!(self.chars.peek(), Some(&(_, c)) if c.is_ascii_digit() || c == '_') {
+			self.bump();
+		}
+
+		let mut is_float = false;
+		if self.chars.peek().map(|&(_, c)| c) == Some('.') {
+			let after_dot = self.peek_nth(1);
+			let starts_range_or_method = after_dot == Some('.') || after_dot.is_some_and(is_ident_start);
+			if !starts_range_or_method {
+				is_float = true;
+				self.bump(); // '.'
+				while matches!(self.chars.peek(), Some(&(_, c)) if c.is_ascii_digit() || c == '_')
+				{
+					self.bump();
+				}
+			}
+		}
+
+		if matches!(self.chars.peek(), Some(&(_, c)) if c == 'e' || c == 'E') {
+			let sign_offset = if matches!(self.peek_nth(1), Some('+') | Some('-')) { 2 } else { 1 };
+			if self.peek_nth(sign_offset).is_some_and(|c| c.is_ascii_digit()) {
+				is_float = true;
+				self.bump(); // e/E
+				if matches!(self.chars.peek(), Some(&(_, c)) if c == '+' || c == '-') {
+					self.bump();
+				}
+				while matches!(self.chars.peek(), Some(&(_, c)) if c.is_ascii_digit()) {
+					self.bump();
+				}
+			}
+		}
+
+		// Numeric type suffix, e.g. `u32`, `f64`.
+		while matches!(self.chars.peek(), Some(&(_, c)) if c.is_ascii_alphanumeric() || c == '_') {
+			self.bump();
+		}
+
+		if is_float { TokenKind::FloatLiteral } else { TokenKind::IntLiteral }
+	}
+
+	fn lex_ident(&mut self) -> TokenKind {
+		self.bump();
+		while matches!(self.chars.peek(), Some(&(_, c)) if is_ident_continue(c)) {
+			self.bump();
+		}
+		TokenKind::Ident
+	}
+
+	fn match_operator(&mut self) -> Option<TokenKind> {
+		let rest = &self.src[self.pos()..];
+		for op in OPERATORS {
+			if rest.starts_with(op) {
+				let target = self.pos() + op.len();
+				self.advance_to(target);
+				return Some(TokenKind::Punct);
+			}
+		}
+		None
+	}
+
+	/// Emits a single [`TokenKind::Error`] token covering the run of
+	/// unrecognized characters up to (but not including) the next
+	/// whitespace character or EOF, so the lexer can resynchronize.
+	fn lex_error(&mut self) -> TokenKind {
+		self.bump();
+		while matches!(self.chars.peek(), Some(&(_, c)) if !c.is_whitespace()) {
+			self.bump();
+		}
+		TokenKind::Error
+	}
+}
+
+fn is_ident_start(c: char) -> bool {
+	c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_continue(c: char) -> bool {
+	c == '_' || c.is_alphanumeric()
+}
+
+fn is_ascii_punct(c: char) -> bool {
+	c.is_ascii_punctuation()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn kinds(src: &str) -> Vec<(&str, TokenKind)> {
+		tokenize(src).into_iter().map(|t| (&src[t.span.clone()], t.kind)).collect()
+	}
+
+	#[test]
+	fn range_after_int_literal_does_not_become_a_float() {
+		assert_eq!(kinds("0..3"), vec![("0", TokenKind::IntLiteral), ("..", TokenKind::Punct), ("3", TokenKind::IntLiteral)]);
+	}
+
+	#[test]
+	fn bare_exponent_is_a_float_literal() {
+		assert_eq!(kinds("1e10"), vec![("1e10", TokenKind::FloatLiteral)]);
+	}
+
+	#[test]
+	fn signed_exponent_is_a_single_float_literal() {
+		assert_eq!(kinds("1e+10"), vec![("1e+10", TokenKind::FloatLiteral)]);
+		assert_eq!(kinds("1e-10"), vec![("1e-10", TokenKind::FloatLiteral)]);
+	}
+
+	#[test]
+	fn dotted_exponent_is_a_single_float_literal() {
+		assert_eq!(kinds("1.0e10"), vec![("1.0e10", TokenKind::FloatLiteral)]);
+	}
+
+	#[test]
+	fn numeric_suffixes_stay_attached() {
+		assert_eq!(kinds("1_000u32"), vec![("1_000u32", TokenKind::IntLiteral)]);
+		assert_eq!(kinds("0xFFu8"), vec![("0xFFu8", TokenKind::IntLiteral)]);
+		assert_eq!(kinds("3.14f64"), vec![("3.14f64", TokenKind::FloatLiteral)]);
+	}
+
+	#[test]
+	fn lifetime_and_label_are_distinguished_from_char_literal() {
+		assert_eq!(kinds("'a"), vec![("'a", TokenKind::Lifetime)]);
+		assert_eq!(kinds("'outer:"), vec![("'outer", TokenKind::Lifetime), (":", TokenKind::Punct)]);
+		assert_eq!(kinds("'x'"), vec![("'x'", TokenKind::CharLiteral)]);
+		assert_eq!(kinds("'\\n'"), vec![("'\\n'", TokenKind::CharLiteral)]);
+	}
+
+	#[test]
+	fn raw_and_byte_strings_are_tokenized_whole() {
+		assert_eq!(kinds(r#"r"hi""#), vec![(r#"r"hi""#, TokenKind::RawStringLiteral { hashes: 0 })]);
+		assert_eq!(kinds(r##"r#"hi"#"##), vec![(r##"r#"hi"#"##, TokenKind::RawStringLiteral { hashes: 1 })]);
+		assert_eq!(kinds(r#"b"hi""#), vec![(r#"b"hi""#, TokenKind::ByteStringLiteral)]);
+		assert_eq!(kinds(r#"br"hi""#), vec![(r#"br"hi""#, TokenKind::RawByteStringLiteral { hashes: 0 })]);
+		assert_eq!(kinds("b'x'"), vec![("b'x'", TokenKind::ByteLiteral)]);
+	}
+
+	#[test]
+	fn doc_comments_are_distinguished_from_plain_comments() {
+		assert_eq!(kinds("/// doc"), vec![("/// doc", TokenKind::DocLineComment { inner: false })]);
+		assert_eq!(kinds("//! inner doc"), vec![("//! inner doc", TokenKind::DocLineComment { inner: true })]);
+		assert_eq!(kinds("// plain"), vec![("// plain", TokenKind::LineComment)]);
+		assert_eq!(kinds("//// also plain"), vec![("//// also plain", TokenKind::LineComment)]);
+	}
+
+	#[test]
+	fn shebang_is_only_recognized_on_first_line_and_not_as_an_inner_attribute() {
+		assert_eq!(kinds("#!/usr/bin/env rustc"), vec![("#!/usr/bin/env rustc", TokenKind::Shebang)]);
+		assert_eq!(
+			kinds("#![allow(dead_code)]"),
+			vec![
+				("#", TokenKind::Punct),
+				("!", TokenKind::Punct),
+				("[", TokenKind::Punct),
+				("allow", TokenKind::Ident),
+				("(", TokenKind::Punct),
+				("dead_code", TokenKind::Ident),
+				(")", TokenKind::Punct),
+				("]", TokenKind::Punct),
+			]
+		);
+	}
+
+	#[test]
+	fn multi_char_operators_max_munch() {
+		assert_eq!(kinds("..="), vec![("..=", TokenKind::Punct)]);
+		assert_eq!(kinds("::"), vec![("::", TokenKind::Punct)]);
+		assert_eq!(kinds("->"), vec![("->", TokenKind::Punct)]);
+		assert_eq!(kinds("=>"), vec![("=>", TokenKind::Punct)]);
+		assert_eq!(
+			kinds("0..=3"),
+			vec![("0", TokenKind::IntLiteral), ("..=", TokenKind::Punct), ("3", TokenKind::IntLiteral)]
+		);
+	}
+
+	#[test]
+	fn raw_identifier_is_a_single_ident_token() {
+		assert_eq!(kinds("r#match"), vec![("r#match", TokenKind::Ident)]);
+		assert_eq!(kinds("r#fn"), vec![("r#fn", TokenKind::Ident)]);
+		// Not confused with a raw string: `r#"..."#` still lexes as one.
+		assert_eq!(kinds(r##"r#"hi"#"##), vec![(r##"r#"hi"#"##, TokenKind::RawStringLiteral { hashes: 1 })]);
+	}
+}
@@ -0,0 +1,7 @@
+//! Tooling crate for working with Rust source text: tokenization, formatting,
+//! macro expansion, and FFI signature extraction.
+
+pub mod ffi;
+pub mod format;
+pub mod lexer;
+pub mod macros;
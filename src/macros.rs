@@ -0,0 +1,724 @@
+//! Parses `macro_rules!` definitions into a structured matcher/transcriber
+//! tree, and expands invocations against them, built on top of
+//! [`crate::lexer`].
+//!
+//! This is a deliberately pragmatic engine, not a full reimplementation of
+//! rustc's macro matcher: fragments other than `tt`/`ident`/`lifetime`/
+//! `literal` are matched greedily, stopping at the next literal token the
+//! matcher expects (or at the end of input), rather than by actually
+//! parsing an expression/type/pattern/etc. That's enough to handle shapes
+//! like `$( $x:expr ),* $(,)?`, the one this crate's own example uses,
+//! without a real expression parser.
+
+use crate::lexer::{self, Token, TokenKind};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The fragment kind after a `$name:` in a matcher, e.g. `expr` in `$x:expr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentSpec {
+	Expr,
+	Ty,
+	Ident,
+	Pat,
+	Tt,
+	Block,
+	Literal,
+	Path,
+	Stmt,
+	Meta,
+	Item,
+	Vis,
+	Lifetime,
+}
+
+impl FragmentSpec {
+	fn parse(name: &str) -> Option<FragmentSpec> {
+		Some(match name {
+			"expr" => FragmentSpec::Expr,
+			"ty" => FragmentSpec::Ty,
+			"ident" => FragmentSpec::Ident,
+			"pat" | "pat_param" => FragmentSpec::Pat,
+			"tt" => FragmentSpec::Tt,
+			"block" => FragmentSpec::Block,
+			"literal" => FragmentSpec::Literal,
+			"path" => FragmentSpec::Path,
+			"stmt" => FragmentSpec::Stmt,
+			"meta" => FragmentSpec::Meta,
+			"item" => FragmentSpec::Item,
+			"vis" => FragmentSpec::Vis,
+			"lifetime" => FragmentSpec::Lifetime,
+			_ => return None,
+		})
+	}
+}
+
+/// The repetition operator after a `$( ... )`, e.g. the `*` in
+/// `$( $x:expr ),*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KleeneOp {
+	ZeroOrMore,
+	OneOrMore,
+	ZeroOrOne,
+}
+
+impl KleeneOp {
+	fn parse(text: &str) -> Option<KleeneOp> {
+		Some(match text {
+			"*" => KleeneOp::ZeroOrMore,
+			"+" => KleeneOp::OneOrMore,
+			"?" => KleeneOp::ZeroOrOne,
+			_ => return None,
+		})
+	}
+}
+
+/// One element of a matcher pattern (the left-hand side of a `macro_rules!`
+/// rule).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+	/// A token that must match literally, e.g. `,` or `fn`.
+	Literal(String),
+	/// `$name:spec`.
+	Fragment { name: String, spec: FragmentSpec },
+	/// `$( matchers )sep op`. `sep` is the separator token between
+	/// repetitions, if any (there is none for `$(,)?`-style groups).
+	Repetition { matchers: Vec<Matcher>, sep: Option<String>, op: KleeneOp },
+}
+
+/// One element of a transcriber (the right-hand side of a `macro_rules!`
+/// rule), emitted when the rule's matcher matches a call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transcriber {
+	/// A token emitted as-is.
+	Literal(String),
+	/// `$name`, substituted with whatever the matcher bound it to.
+	Fragment(String),
+	/// `$( body )sep op`, repeated once per iteration of the metavariables
+	/// bound inside `body`, iterating them in lockstep.
+	Repetition { body: Vec<Transcriber>, sep: Option<String>, op: KleeneOp },
+}
+
+/// One `(matcher) => { transcriber };` arm of a `macro_rules!` definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroRule {
+	pub matcher: Vec<Matcher>,
+	pub transcriber: Vec<Transcriber>,
+}
+
+/// A parsed `macro_rules!` definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroDef {
+	pub name: String,
+	pub rules: Vec<MacroRule>,
+}
+
+/// A token produced by [`expand`].
+///
+/// Unlike [`crate::lexer::Token`], this owns its text rather than carrying a
+/// span: expansion interleaves tokens captured from the call site with
+/// literal tokens from the macro definition, so there's no single source
+/// string left for a span to index into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroToken {
+	pub kind: TokenKind,
+	pub text: String,
+}
+
+/// An error parsing a `macro_rules!` definition or expanding a call against
+/// one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroError {
+	/// The lexer could not tokenize this span of the input.
+	InvalidToken { span: Range<usize> },
+	/// The input doesn't look like a `macro_rules!` definition where one
+	/// was expected, e.g. a missing `=>` or unbalanced delimiters.
+	Syntax { span: Range<usize> },
+	/// A `$name:spec` used a `spec` that isn't one of the documented
+	/// fragment kinds.
+	UnknownFragmentSpec { span: Range<usize> },
+	/// None of the definition's rules matched the call tokens.
+	NoMatchingRule,
+	/// A transcriber referenced `$name`, but the matcher never bound it (or
+	/// bound it as a repeated sequence used outside of a `$( ... )`).
+	UnboundFragment { name: String },
+}
+
+// --- parsing -------------------------------------------------------------
+
+/// Parses a single `macro_rules! name { ... }` (or `(...)`/`[...]`)
+/// definition out of `src`.
+pub fn parse(src: &str) -> Result<MacroDef, MacroError> {
+	let tokens = lexer::tokenize(src);
+	for t in &tokens {
+		if t.kind == TokenKind::Error {
+			return Err(MacroError::InvalidToken { span: t.span.clone() });
+		}
+	}
+	let kept: Vec<&Token> = tokens.iter().filter(|t| t.kind != TokenKind::Whitespace).collect();
+	Parser { kept: &kept, src, pos: 0 }.parse_macro_def()
+}
+
+struct Parser<'a> {
+	kept: &'a [&'a Token],
+	src: &'a str,
+	pos: usize,
+}
+
+impl<'a> Parser<'a> {
+	fn text(&self, i: usize) -> &'a str {
+		&self.src[self.kept[i].span.clone()]
+	}
+
+	fn at_end(&self) -> bool {
+		self.pos >= self.kept.len()
+	}
+
+	fn peek(&self) -> Option<&'a str> {
+		(!self.at_end()).then(|| self.text(self.pos))
+	}
+
+	fn bump(&mut self) -> Option<&'a str> {
+		if self.at_end() {
+			return None;
+		}
+		let t = self.text(self.pos);
+		self.pos += 1;
+		Some(t)
+	}
+
+	fn err_here(&self) -> MacroError {
+		let span = if self.at_end() {
+			self.kept.last().map(|t| t.span.clone()).unwrap_or(0..0)
+		} else {
+			self.kept[self.pos].span.clone()
+		};
+		MacroError::Syntax { span }
+	}
+
+	fn expect(&mut self, text: &str) -> Result<(), MacroError> {
+		if self.peek() == Some(text) {
+			self.pos += 1;
+			Ok(())
+		} else {
+			Err(self.err_here())
+		}
+	}
+
+	/// Finds the index (into `self.kept`) of the delimiter that closes the
+	/// open delimiter at `open_idx`.
+	fn find_close(&self, open_idx: usize) -> Result<usize, MacroError> {
+		let close = match self.text(open_idx) {
+			"(" => ")",
+			"[" => "]",
+			"{" => "}",
+			_ => return Err(MacroError::Syntax { span: self.kept[open_idx].span.clone() }),
+		};
+		let mut depth = 1usize;
+		let mut i = open_idx + 1;
+		while i < self.kept.len() {
+			match self.text(i) {
+				"(" | "[" | "{" => depth += 1,
+				t @ (")" | "]" | "}") => {
+					depth -= 1;
+					if depth == 0 {
+						return if t == close {
+							Ok(i)
+						} else {
+							Err(MacroError::Syntax { span: self.kept[i].span.clone() })
+						};
+					}
+				}
+				_ => {}
+			}
+			i += 1;
+		}
+		Err(MacroError::Syntax { span: self.kept[open_idx].span.clone() })
+	}
+
+	fn parse_macro_def(&mut self) -> Result<MacroDef, MacroError> {
+		self.expect("macro_rules")?;
+		self.expect("!")?;
+		let name = self.bump().ok_or_else(|| self.err_here())?.to_string();
+
+		let open_idx = self.pos;
+		if !matches!(self.peek(), Some("{") | Some("(") | Some("[")) {
+			return Err(self.err_here());
+		}
+		let close_idx = self.find_close(open_idx)?;
+		self.pos = open_idx + 1;
+
+		let mut rules = Vec::new();
+		while self.pos < close_idx {
+			rules.push(self.parse_rule()?);
+			if self.peek() == Some(";") {
+				self.pos += 1;
+			}
+		}
+		self.pos = close_idx + 1;
+
+		Ok(MacroDef { name, rules })
+	}
+
+	fn parse_rule(&mut self) -> Result<MacroRule, MacroError> {
+		let matcher_open = self.pos;
+		if !matches!(self.peek(), Some("{") | Some("(") | Some("[")) {
+			return Err(self.err_here());
+		}
+		let matcher_close = self.find_close(matcher_open)?;
+		self.pos = matcher_open + 1;
+		let matcher = self.parse_matcher_seq(matcher_close)?;
+		self.pos = matcher_close + 1;
+
+		self.expect("=>")?;
+
+		let body_open = self.pos;
+		if !matches!(self.peek(), Some("{") | Some("(") | Some("[")) {
+			return Err(self.err_here());
+		}
+		let body_close = self.find_close(body_open)?;
+		self.pos = body_open + 1;
+		let transcriber = self.parse_transcriber_seq(body_close)?;
+		self.pos = body_close + 1;
+
+		Ok(MacroRule { matcher, transcriber })
+	}
+
+	fn parse_matcher_seq(&mut self, end: usize) -> Result<Vec<Matcher>, MacroError> {
+		let mut out = Vec::new();
+		while self.pos < end {
+			if self.peek() == Some("$") {
+				self.pos += 1;
+				if self.peek() == Some("(") {
+					let open = self.pos;
+					let close = self.find_close(open)?;
+					self.pos = open + 1;
+					let inner = self.parse_matcher_seq(close)?;
+					self.pos = close + 1;
+					let (sep, op) = self.parse_repetition_tail()?;
+					out.push(Matcher::Repetition { matchers: inner, sep, op });
+				} else {
+					let name = self.bump().ok_or_else(|| self.err_here())?.to_string();
+					self.expect(":")?;
+					let spec_span = self.kept.get(self.pos).map(|t| t.span.clone()).unwrap_or(0..0);
+					let spec_name = self.bump().ok_or_else(|| self.err_here())?;
+					let spec = FragmentSpec::parse(spec_name)
+						.ok_or(MacroError::UnknownFragmentSpec { span: spec_span })?;
+					out.push(Matcher::Fragment { name, spec });
+				}
+			} else {
+				out.push(Matcher::Literal(self.bump().unwrap().to_string()));
+			}
+		}
+		Ok(out)
+	}
+
+	fn parse_transcriber_seq(&mut self, end: usize) -> Result<Vec<Transcriber>, MacroError> {
+		let mut out = Vec::new();
+		while self.pos < end {
+			if self.peek() == Some("$") {
+				self.pos += 1;
+				if self.peek() == Some("(") {
+					let open = self.pos;
+					let close = self.find_close(open)?;
+					self.pos = open + 1;
+					let body = self.parse_transcriber_seq(close)?;
+					self.pos = close + 1;
+					let (sep, op) = self.parse_repetition_tail()?;
+					out.push(Transcriber::Repetition { body, sep, op });
+				} else {
+					let name = self.bump().ok_or_else(|| self.err_here())?.to_string();
+					out.push(Transcriber::Fragment(name));
+				}
+			} else {
+				out.push(Transcriber::Literal(self.bump().unwrap().to_string()));
+			}
+		}
+		Ok(out)
+	}
+
+	/// Parses the `sep op` (or just `op`) that follows a `$( ... )` group,
+	/// e.g. the `,` and `*` in `$( $x:expr ),*`, or just the `?` in
+	/// `$(,)?`.
+	fn parse_repetition_tail(&mut self) -> Result<(Option<String>, KleeneOp), MacroError> {
+		let first = self.peek().ok_or_else(|| self.err_here())?;
+		if let Some(op) = KleeneOp::parse(first) {
+			self.pos += 1;
+			return Ok((None, op));
+		}
+		let sep = first.to_string();
+		self.pos += 1;
+		let op_text = self.peek().ok_or_else(|| self.err_here())?;
+		let op = KleeneOp::parse(op_text).ok_or_else(|| self.err_here())?;
+		self.pos += 1;
+		Ok((Some(sep), op))
+	}
+}
+
+// --- matching & expansion -------------------------------------------------
+
+/// A single metavariable's capture: either one fragment's tokens, or (for a
+/// metavariable bound inside a repetition) one `Binding` per iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Binding {
+	Single(Vec<MacroToken>),
+	Sequence(Vec<Binding>),
+}
+
+/// Matches `def`'s rules against `call_tokens` (the tokens inside a macro
+/// invocation's delimiters, e.g. the `1, 2, 3` in `my_vec![1, 2, 3]`) in
+/// order, and expands the first rule that matches in full.
+///
+/// `call_src` is the source text `call_tokens`' spans index into. The
+/// result is a flat token stream rather than [`crate::lexer::Token`]s: see
+/// [`MacroToken`] for why.
+pub fn expand(
+	def: &MacroDef,
+	call_tokens: &[Token],
+	call_src: &str,
+) -> Result<Vec<MacroToken>, MacroError> {
+	let call: Vec<&Token> = call_tokens.iter().filter(|t| t.kind != TokenKind::Whitespace).collect();
+
+	for rule in &def.rules {
+		let mut bindings = HashMap::new();
+		if let Some(end) = match_seq(&rule.matcher, &call, call_src, 0, &[], &mut bindings) {
+			if end == call.len() {
+				return transcribe_seq(&rule.transcriber, &bindings);
+			}
+		}
+	}
+	Err(MacroError::NoMatchingRule)
+}
+
+/// Tries to match `matchers` against `call[pos..]`, consuming as much as
+/// the pattern dictates, and returns the new position on success.
+///
+/// `stop_texts` are extra literal token texts that end a trailing greedy
+/// fragment when there's no following matcher to supply one — used when
+/// matching the body of a repetition, where the only terminator is the
+/// repetition's own separator (or whatever follows the repetition).
+fn match_seq(
+	matchers: &[Matcher],
+	call: &[&Token],
+	call_src: &str,
+	mut pos: usize,
+	stop_texts: &[&str],
+	bindings: &mut HashMap<String, Binding>,
+) -> Option<usize> {
+	for (i, m) in matchers.iter().enumerate() {
+		match m {
+			Matcher::Literal(text) => {
+				let tok = call.get(pos)?;
+				if &call_src[tok.span.clone()] != text {
+					return None;
+				}
+				pos += 1;
+			}
+			Matcher::Fragment { name, spec } => {
+				let stop: Vec<&str> = match matchers.get(i + 1) {
+					Some(Matcher::Literal(t)) => vec![t.as_str()],
+					Some(_) => return None, // ambiguous: no lookahead past a non-literal
+					None => stop_texts.to_vec(),
+				};
+				let (consumed, new_pos) = match_fragment(*spec, call, call_src, pos, &stop)?;
+				bindings.insert(name.clone(), Binding::Single(consumed));
+				pos = new_pos;
+			}
+			Matcher::Repetition { matchers: inner, sep, op } => {
+				// The stop set for matching this repetition's body must
+				// include not just its own separator (or the literal that
+				// follows it here), but also whatever terminates the
+				// enclosing context (`stop_texts`) — otherwise a nested
+				// repetition's trailing greedy fragment can't tell its own
+				// separator apart from the outer group's, and swallows the
+				// outer separator/closer whole (e.g. `$( $( $x:expr ),* );*`
+				// against `1, 2, 3; 4, 5` would let the inner `expr` eat
+				// straight through the `;`).
+				let tail_stop: Vec<&str> = {
+					let mut v: Vec<&str> = Vec::new();
+					if let Some(s) = sep {
+						v.push(s.as_str());
+					}
+					match matchers.get(i + 1) {
+						Some(Matcher::Literal(t)) => v.push(t.as_str()),
+						Some(_) if sep.is_none() => return None, // ambiguous: no sep and no literal lookahead past a non-literal matcher
+						_ => {}
+					}
+					v.extend(stop_texts.iter().copied());
+					v
+				};
+				let names = fragment_names(inner);
+				let mut seqs: HashMap<String, Vec<Binding>> =
+					names.iter().map(|n| (n.clone(), Vec::new())).collect();
+				let mut count = 0usize;
+				loop {
+					let mut inner_bindings = HashMap::new();
+					let Some(new_pos) =
+						match_seq(inner, call, call_src, pos, &tail_stop, &mut inner_bindings)
+					else {
+						break;
+					};
+					if new_pos == pos && !inner.is_empty() {
+						break; // zero-width match: stop to avoid looping forever
+					}
+					for n in &names {
+						if let Some(b) = inner_bindings.remove(n) {
+							seqs.get_mut(n).unwrap().push(b);
+						}
+					}
+					pos = new_pos;
+					count += 1;
+
+					if *op == KleeneOp::ZeroOrOne {
+						break;
+					}
+					if let Some(sep_text) = sep {
+						if call.get(pos).map(|t| &call_src[t.span.clone()]) == Some(sep_text.as_str()) {
+							pos += 1;
+						} else {
+							break;
+						}
+					}
+				}
+				if *op == KleeneOp::OneOrMore && count == 0 {
+					return None;
+				}
+				for n in names {
+					bindings.insert(n.clone(), Binding::Sequence(seqs.remove(&n).unwrap_or_default()));
+				}
+			}
+		}
+	}
+	Some(pos)
+}
+
+/// Matches a single fragment capture at `call[pos]`, returning its captured
+/// tokens and the position just past them.
+fn match_fragment(
+	spec: FragmentSpec,
+	call: &[&Token],
+	call_src: &str,
+	pos: usize,
+	stop: &[&str],
+) -> Option<(Vec<MacroToken>, usize)> {
+	match spec {
+		FragmentSpec::Ident => {
+			let tok = call.get(pos)?;
+			(tok.kind == TokenKind::Ident).then(|| (vec![to_macro_token(tok, call_src)], pos + 1))
+		}
+		FragmentSpec::Lifetime => {
+			let tok = call.get(pos)?;
+			(tok.kind == TokenKind::Lifetime).then(|| (vec![to_macro_token(tok, call_src)], pos + 1))
+		}
+		FragmentSpec::Literal => {
+			let tok = call.get(pos)?;
+			is_literal_kind(&tok.kind).then(|| (vec![to_macro_token(tok, call_src)], pos + 1))
+		}
+		FragmentSpec::Tt => {
+			let tok = call.get(pos)?;
+			let text = &call_src[tok.span.clone()];
+			if matches!(text, "(" | "[" | "{") {
+				let close = find_call_close(call, call_src, pos)?;
+				let toks = call[pos..=close].iter().map(|t| to_macro_token(t, call_src)).collect();
+				Some((toks, close + 1))
+			} else {
+				Some((vec![to_macro_token(tok, call_src)], pos + 1))
+			}
+		}
+		// expr, ty, pat, block, path, stmt, meta, item, vis: no real parser
+		// for these, so consume greedily (respecting bracket nesting) up to
+		// the next stop token at depth 0, or the end of input.
+		_ => {
+			call.get(pos)?;
+			let mut i = pos;
+			let mut depth = 0i32;
+			while i < call.len() {
+				let text = &call_src[call[i].span.clone()];
+				if depth == 0 && stop.contains(&text) {
+					break;
+				}
+				match text {
+					"(" | "[" | "{" => depth += 1,
+					")" | "]" | "}" => {
+						// An unmatched closer at depth 0 belongs to some
+						// enclosing group (e.g. the `]` around a nested
+						// `$( ... ),*`), not to this fragment — stop instead
+						// of letting `depth` go negative and walking straight
+						// through it.
+						if depth == 0 {
+							break;
+						}
+						depth -= 1;
+					}
+					_ => {}
+				}
+				i += 1;
+			}
+			if i == pos {
+				return None;
+			}
+			Some((call[pos..i].iter().map(|t| to_macro_token(t, call_src)).collect(), i))
+		}
+	}
+}
+
+fn find_call_close(call: &[&Token], call_src: &str, open: usize) -> Option<usize> {
+	let close_text = match &call_src[call[open].span.clone()] {
+		"(" => ")",
+		"[" => "]",
+		"{" => "}",
+		_ => return None,
+	};
+	let mut depth = 1usize;
+	let mut i = open + 1;
+	while i < call.len() {
+		match &call_src[call[i].span.clone()] {
+			"(" | "[" | "{" => depth += 1,
+			t @ (")" | "]" | "}") => {
+				depth -= 1;
+				if depth == 0 {
+					return (t == close_text).then_some(i);
+				}
+			}
+			_ => {}
+		}
+		i += 1;
+	}
+	None
+}
+
+fn is_literal_kind(kind: &TokenKind) -> bool {
+	matches!(
+		kind,
+		TokenKind::IntLiteral
+			| TokenKind::FloatLiteral
+			| TokenKind::CharLiteral
+			| TokenKind::ByteLiteral
+			| TokenKind::StringLiteral
+			| TokenKind::ByteStringLiteral
+			| TokenKind::RawStringLiteral { .. }
+			| TokenKind::RawByteStringLiteral { .. }
+	)
+}
+
+fn to_macro_token(tok: &Token, src: &str) -> MacroToken {
+	MacroToken { kind: tok.kind.clone(), text: src[tok.span.clone()].to_string() }
+}
+
+fn fragment_names(matchers: &[Matcher]) -> Vec<String> {
+	let mut names = Vec::new();
+	for m in matchers {
+		match m {
+			Matcher::Fragment { name, .. } => names.push(name.clone()),
+			Matcher::Repetition { matchers, .. } => names.extend(fragment_names(matchers)),
+			Matcher::Literal(_) => {}
+		}
+	}
+	names
+}
+
+fn transcriber_fragment_names(transcribers: &[Transcriber]) -> Vec<String> {
+	let mut names = Vec::new();
+	for t in transcribers {
+		match t {
+			Transcriber::Fragment(name) => names.push(name.clone()),
+			Transcriber::Repetition { body, .. } => names.extend(transcriber_fragment_names(body)),
+			Transcriber::Literal(_) => {}
+		}
+	}
+	names
+}
+
+/// The token kind a piece of literal transcriber text would lex as, so
+/// emitted `MacroToken`s carry an accurate `kind` rather than a made-up one.
+fn literal_kind(text: &str) -> TokenKind {
+	lexer::tokenize(text).into_iter().next().map(|t| t.kind).unwrap_or(TokenKind::Punct)
+}
+
+fn transcribe_seq(
+	transcribers: &[Transcriber],
+	bindings: &HashMap<String, Binding>,
+) -> Result<Vec<MacroToken>, MacroError> {
+	let mut out = Vec::new();
+	for t in transcribers {
+		transcribe_one(t, bindings, &mut out)?;
+	}
+	Ok(out)
+}
+
+fn transcribe_one(
+	t: &Transcriber,
+	bindings: &HashMap<String, Binding>,
+	out: &mut Vec<MacroToken>,
+) -> Result<(), MacroError> {
+	match t {
+		Transcriber::Literal(text) => {
+			out.push(MacroToken { kind: literal_kind(text), text: text.clone() });
+		}
+		Transcriber::Fragment(name) => match bindings.get(name) {
+			Some(Binding::Single(toks)) => out.extend(toks.iter().cloned()),
+			_ => return Err(MacroError::UnboundFragment { name: name.clone() }),
+		},
+		Transcriber::Repetition { body, sep, op: _ } => {
+			let names = transcriber_fragment_names(body);
+			let len = names
+				.iter()
+				.filter_map(|n| match bindings.get(n) {
+					Some(Binding::Sequence(s)) => Some(s.len()),
+					_ => None,
+				})
+				.max()
+				.unwrap_or(0);
+
+			for idx in 0..len {
+				if idx > 0 {
+					if let Some(sep_text) = sep {
+						out.push(MacroToken { kind: literal_kind(sep_text), text: sep_text.clone() });
+					}
+				}
+				let mut iter_bindings = bindings.clone();
+				for n in &names {
+					if let Some(Binding::Sequence(seq)) = bindings.get(n) {
+						if let Some(b) = seq.get(idx) {
+							iter_bindings.insert(n.clone(), b.clone());
+						}
+					}
+				}
+				for inner in body {
+					transcribe_one(inner, &iter_bindings, out)?;
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn expand_str(def_src: &str, call_src: &str) -> String {
+		let def = parse(def_src).unwrap();
+		let call_tokens = lexer::tokenize(call_src);
+		let out = expand(&def, &call_tokens, call_src).unwrap();
+		out.iter().map(|t| t.text.as_str()).collect::<Vec<_>>().join(" ")
+	}
+
+	#[test]
+	fn simple_comma_repetition_expands() {
+		let def = "macro_rules! my_vec { ( $( $x:expr ),* $(,)? ) => { vec![ $( $x ),* ] }; }";
+		assert_eq!(expand_str(def, "1, 2, 3"), "vec ! [ 1 , 2 , 3 ]");
+	}
+
+	#[test]
+	fn nested_repetitions_iterate_in_lockstep() {
+		let def = "macro_rules! m { ( $( $( $x:expr ),* );* ) => { $( row($( $x ),*); )* }; }";
+		assert_eq!(expand_str(def, "1, 2, 3; 4, 5"), "row ( 1 , 2 , 3 ) ; row ( 4 , 5 ) ;");
+	}
+
+	#[test]
+	fn bracket_wrapped_nested_repetitions_match() {
+		let def = "macro_rules! m2 { ( $( [ $( $x:expr ),* ] ),* ) => { $( group($( $x ),*); )* }; }";
+		assert_eq!(expand_str(def, "[1,2,3],[4,5]"), "group ( 1 , 2 , 3 ) ; group ( 4 , 5 ) ;");
+	}
+}